@@ -1,4 +1,5 @@
 pub mod cpu;
+pub mod disk;
 pub mod gpu;
 pub mod process;
 
@@ -7,8 +8,10 @@ use nvml_wrapper::Nvml;
 use std::fs;
 use std::io::Write;
 use std::path::Path;
+use crate::format::OutputFormat;
 use crate::metrics::Snapshot;
 use crate::mode::AgentMode;
+use process::GpuUtilWatermarks;
 
 
 pub fn take_global_snapshot(
@@ -16,6 +19,9 @@ pub fn take_global_snapshot(
     nvml: Option<&Nvml>,
     output_folder: &str,
     mode: AgentMode,
+    elapsed_secs: f64,
+    gpu_util_watermarks: &mut GpuUtilWatermarks,
+    format: OutputFormat,
 ) -> Result<String, Box<dyn std::error::Error>> {
     // Create output folder if it doesn't exist
     fs::create_dir_all(output_folder)?;
@@ -28,7 +34,9 @@ pub fn take_global_snapshot(
     // Collect snapshots from all subsystems
     let cpus_snapshot = cpu::take_cpus_snapshot(system, mode);
     let gpus_snapshot = gpu::take_gpus_snapshot(nvml);
-    let processes_snapshot = process::take_processes_snapshot(system, nvml);
+    let processes_snapshot =
+        process::take_processes_snapshot(system, nvml, elapsed_secs, gpu_util_watermarks, mode);
+    let disks_snapshot = disk::take_disks_snapshot();
 
     // Build the complete snapshot
     let snapshot = Snapshot {
@@ -36,19 +44,41 @@ pub fn take_global_snapshot(
         cpus_snapshot,
         gpus_snapshot,
         processes_snapshot,
+        disks_snapshot,
     };
 
-    // Serialize to JSON
-    let json_data = serde_json::to_string_pretty(&snapshot)?;
-
-    // Create filename with timestamp and hostname
     let hostname = System::host_name().unwrap_or_else(|| "unknown".to_string());
-    let filename = format!("snapshot_{}_{}.json", hostname, timestamp);
-    let filepath = Path::new(output_folder).join(&filename);
 
-    // Write to file
-    let mut file = fs::File::create(&filepath)?;
-    file.write_all(json_data.as_bytes())?;
+    match format {
+        OutputFormat::Files => {
+            // Serialize to JSON
+            let json_data = serde_json::to_string_pretty(&snapshot)?;
+
+            // Create filename with timestamp and hostname
+            let filename = format!("snapshot_{}_{}.json", hostname, timestamp);
+            let filepath = Path::new(output_folder).join(&filename);
+
+            // Write to file
+            let mut file = fs::File::create(&filepath)?;
+            file.write_all(json_data.as_bytes())?;
+
+            Ok(filepath.to_string_lossy().to_string())
+        }
+        OutputFormat::Ndjson => {
+            // One compact JSON line per tick, appended to a single rolling file per host.
+            let json_data = serde_json::to_string(&snapshot)?;
+
+            let filename = format!("snapshots_{}.ndjson", hostname);
+            let filepath = Path::new(output_folder).join(&filename);
+
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&filepath)?;
+            writeln!(file, "{}", json_data)?;
+            file.flush()?;
 
-    Ok(filepath.to_string_lossy().to_string())
+            Ok(filepath.to_string_lossy().to_string())
+        }
+    }
 }
\ No newline at end of file
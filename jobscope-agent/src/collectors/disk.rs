@@ -0,0 +1,21 @@
+use sysinfo::Disks;
+use crate::metrics::{DisksSnapshot, DiskInfo};
+
+pub fn take_disks_snapshot() -> DisksSnapshot {
+    let disks = Disks::new_with_refreshed_list();
+
+    let disks_info = disks.iter().map(_collect_disk_info).collect();
+
+    DisksSnapshot { disks: disks_info }
+}
+
+fn _collect_disk_info(disk: &sysinfo::Disk) -> DiskInfo {
+    DiskInfo {
+        mount_point: disk.mount_point().to_string_lossy().to_string(),
+        name: disk.name().to_str().map(|s| s.to_string()),
+        file_system: disk.file_system().to_str().map(|s| s.to_string()),
+        total_bytes: disk.total_space(),
+        available_bytes: disk.available_space(),
+        is_removable: disk.is_removable(),
+    }
+}
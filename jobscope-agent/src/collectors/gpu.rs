@@ -1,5 +1,6 @@
 use nvml_wrapper::Nvml;
-use crate::metrics::{GPUsSnapshot, GPUInfo, GPUIndex, MemoryLoad};
+use nvml_wrapper::enum_wrappers::device::{Clock, TemperatureSensor};
+use crate::metrics::{GPUsSnapshot, GPUInfo, GPUIndex, GPUClocks, MemoryLoad};
 
 
 pub fn take_gpus_snapshot(nvml: Option<&Nvml>) -> GPUsSnapshot {
@@ -41,6 +42,19 @@ fn _collect_gpu_info(nvml: &Nvml, index: GPUIndex) -> Option<GPUInfo> {
         Err(_) => return None,
     };
 
+    let temperature_celsius = device.temperature(TemperatureSensor::Gpu).ok();
+
+    let power_usage_watts = device.power_usage().ok().map(|mw| mw as f32 / 1000.0);
+
+    let power_limit_watts = device
+        .power_management_limit()
+        .ok()
+        .map(|mw| mw as f32 / 1000.0);
+
+    let fan_speed_percent = device.fan_speed(0).ok();
+
+    let clocks = _collect_gpu_clocks(&device);
+
     Some(GPUInfo {
         index,
         name,
@@ -49,5 +63,24 @@ fn _collect_gpu_info(nvml: &Nvml, index: GPUIndex) -> Option<GPUInfo> {
             used_bytes: memory_info.used,
             total_bytes: memory_info.total,
         },
+        temperature_celsius,
+        power_usage_watts,
+        power_limit_watts,
+        fan_speed_percent,
+        clocks,
+    })
+}
+
+fn _collect_gpu_clocks(device: &nvml_wrapper::Device) -> Option<GPUClocks> {
+    let graphics_mhz = device.clock_info(Clock::Graphics).ok()?;
+    let sm_mhz = device.clock_info(Clock::SM).ok()?;
+    let memory_mhz = device.clock_info(Clock::Memory).ok()?;
+    let video_mhz = device.clock_info(Clock::Video).ok()?;
+
+    Some(GPUClocks {
+        graphics_mhz,
+        sm_mhz,
+        memory_mhz,
+        video_mhz,
     })
 }
\ No newline at end of file
@@ -4,9 +4,36 @@ use nvml_wrapper::enums::device::UsedGpuMemory;
 use std::collections::HashMap;
 use std::fs;
 use std::os::unix::fs::MetadataExt;
-use crate::metrics::{ProcessesSnapshot, ProcessInfo, GPUIndex, CPUIndex};
+use std::path::{Path, PathBuf};
+use crate::metrics::{ProcessesSnapshot, ProcessInfo, GPUIndex, CPUIndex, GpuProcessKind, CgroupUsage, MemoryLoad};
+use crate::mode::AgentMode;
 
-pub fn refresh_user_processes(system: &mut System) {
+/// Per-device NVML process-utilization watermark (microseconds since epoch), so each
+/// call to `process_utilization_stats` only returns samples newer than the last refresh.
+pub type GpuUtilWatermarks = HashMap<GPUIndex, u64>;
+
+pub fn refresh_user_processes(system: &mut System, mode: AgentMode) {
+    let pids = match mode {
+        AgentMode::Local => _pids_by_uid(),
+        AgentMode::Slurm => _resolve_job_cgroup()
+            .map(|cgroup| _pids_from_cgroup(&cgroup))
+            .unwrap_or_else(_pids_by_uid),
+    };
+
+    let mut pids = pids;
+    // Also include existing PIDs to ensure they are checked (and removed if dead)
+    for pid in system.processes().keys() {
+        if !pids.contains(pid) {
+            pids.push(*pid);
+        }
+    }
+
+    system.refresh_processes_specifics(ProcessesToUpdate::Some(&pids), true, ProcessRefreshKind::everything());
+}
+
+/// Scopes to every PID owned by the current user across `/proc`, used on a node that isn't
+/// Slurm-managed (or where the job's cgroup can't be resolved).
+fn _pids_by_uid() -> Vec<Pid> {
     let my_uid = fs::metadata("/proc/self").map(|m| m.uid()).unwrap_or(0);
     let mut pids = Vec::new();
 
@@ -14,7 +41,7 @@ pub fn refresh_user_processes(system: &mut System) {
         for entry in entries.flatten() {
             let path = entry.path();
             if !path.is_dir() { continue; }
-            
+
             if let Some(file_name) = path.file_name().and_then(|s| s.to_str()) {
                 if let Ok(pid_val) = file_name.parse::<u32>() {
                     // Check owner
@@ -27,41 +54,199 @@ pub fn refresh_user_processes(system: &mut System) {
             }
         }
     }
-    
-    // Also include existing PIDs to ensure they are checked (and removed if dead)
-    for pid in system.processes().keys() {
-        if !pids.contains(pid) {
-            pids.push(*pid);
+
+    pids
+}
+
+/// Reads the PIDs tracked by the job's cgroup instead of walking `/proc`, so a shared Slurm
+/// node only reports the processes belonging to the monitored job/step.
+fn _pids_from_cgroup(cgroup: &Path) -> Vec<Pid> {
+    let procs_path = cgroup.join("cgroup.procs");
+    match fs::read_to_string(&procs_path) {
+        Ok(contents) => contents
+            .lines()
+            .filter_map(|line| line.trim().parse::<u32>().ok())
+            .map(Pid::from_u32)
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Resolves the cgroup directory for the current Slurm job/step from `SLURM_JOB_ID`/
+/// `SLURM_STEP_ID`, supporting both the cgroup v2 unified hierarchy and the cgroup v1
+/// per-controller layout (`.../slurm/uid_*/job_*/step_*`).
+fn _resolve_job_cgroup() -> Option<PathBuf> {
+    let job_id = std::env::var("SLURM_JOB_ID").ok()?;
+    let step_id = std::env::var("SLURM_STEP_ID").ok();
+
+    // cgroup v2: unified hierarchy, no per-controller subdirectories.
+    if Path::new("/sys/fs/cgroup/cgroup.controllers").exists() {
+        if let Some(path) = _find_job_dir(Path::new("/sys/fs/cgroup"), &job_id, step_id.as_deref(), 4) {
+            return Some(path);
         }
     }
-    
-    system.refresh_processes_specifics(ProcessesToUpdate::Some(&pids), true, ProcessRefreshKind::everything());
+
+    // cgroup v1: memory accounting lives under its own controller root, mirroring the same
+    // slurm/uid_<uid>/job_<job_id>/step_<step_id> layout as the other controllers.
+    _resolve_job_cgroup_v1(&job_id, step_id.as_deref(), &["memory"])
+}
+
+/// Resolves the cgroup v1 job/step directory under the `cpu,cpuacct` or `cpuacct` controller,
+/// or the unified cgroup v2 directory if the host uses v2 (where every controller shares the
+/// same hierarchy).
+/// Used to find the `cpu,cpuacct`/`cpuacct` controller separately from `_resolve_job_cgroup`'s
+/// `memory` controller, since cgroup v1 mounts each controller at its own root.
+fn _resolve_job_cpu_cgroup() -> Option<PathBuf> {
+    let job_id = std::env::var("SLURM_JOB_ID").ok()?;
+    let step_id = std::env::var("SLURM_STEP_ID").ok();
+
+    if Path::new("/sys/fs/cgroup/cgroup.controllers").exists() {
+        return _find_job_dir(Path::new("/sys/fs/cgroup"), &job_id, step_id.as_deref(), 4);
+    }
+
+    _resolve_job_cgroup_v1(&job_id, step_id.as_deref(), &["cpu,cpuacct", "cpuacct"])
+}
+
+/// Shared cgroup v1 lookup: tries each controller root in turn, returning the first job/step
+/// directory found under `slurm/uid_<uid>/...`.
+fn _resolve_job_cgroup_v1(job_id: &str, step_id: Option<&str>, controllers: &[&str]) -> Option<PathBuf> {
+    let my_uid = fs::metadata("/proc/self").map(|m| m.uid()).unwrap_or(0);
+    for controller in controllers {
+        let base = Path::new("/sys/fs/cgroup").join(controller).join("slurm").join(format!("uid_{}", my_uid));
+        if let Some(path) = _find_job_dir(&base, job_id, step_id, 2) {
+            return Some(path);
+        }
+    }
+
+    None
+}
+
+/// Walks down from `root` looking for a directory named `job_<job_id>` (optionally followed
+/// by `step_<step_id>`), up to `max_depth` levels deep.
+fn _find_job_dir(root: &Path, job_id: &str, step_id: Option<&str>, max_depth: u32) -> Option<PathBuf> {
+    if max_depth == 0 || !root.is_dir() {
+        return None;
+    }
+
+    let entries = fs::read_dir(root).ok()?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_dir() { continue; }
+        let name = path.file_name()?.to_str()?.to_string();
+
+        if name == format!("job_{}", job_id) {
+            if let Some(step_id) = step_id {
+                let step_dir = path.join(format!("step_{}", step_id));
+                if step_dir.is_dir() {
+                    return Some(step_dir);
+                }
+            }
+            return Some(path);
+        }
+
+        if let Some(found) = _find_job_dir(&path, job_id, step_id, max_depth - 1) {
+            return Some(found);
+        }
+    }
+
+    None
+}
+
+/// Reads job-wide memory and CPU accounting straight from the Slurm job's cgroup.
+fn _collect_cgroup_usage() -> Option<CgroupUsage> {
+    let cgroup = _resolve_job_cgroup()?;
+
+    let (used_bytes, total_bytes) = if let Some(used) = _read_cgroup_u64(&cgroup, "memory.current") {
+        let total = _read_cgroup_u64(&cgroup, "memory.max").unwrap_or(u64::MAX);
+        (used, total)
+    } else {
+        let used = _read_cgroup_u64(&cgroup, "memory.usage_in_bytes")?;
+        let total = _read_cgroup_u64(&cgroup, "memory.limit_in_bytes").unwrap_or(u64::MAX);
+        (used, total)
+    };
+
+    // On cgroup v1 the CPU controller is mounted separately from `memory`, so `cpuacct.usage`
+    // has to be read from its own `cpu,cpuacct`/`cpuacct` directory rather than `cgroup` above.
+    let cpu_usage_usec = _read_cgroup_cpu_stat_usec(&cgroup)
+        .or_else(|| {
+            let cpu_cgroup = _resolve_job_cpu_cgroup()?;
+            _read_cgroup_cpu_stat_usec(&cpu_cgroup)
+                .or_else(|| _read_cgroup_u64(&cpu_cgroup, "cpuacct.usage").map(|ns| ns / 1_000))
+        })
+        .unwrap_or(0);
+
+    Some(CgroupUsage {
+        memory: MemoryLoad { used_bytes, total_bytes },
+        cpu_usage_usec,
+    })
+}
+
+fn _read_cgroup_u64(cgroup: &Path, file: &str) -> Option<u64> {
+    fs::read_to_string(cgroup.join(file)).ok()?.trim().parse().ok()
 }
 
-/// Takes a snapshot of all running processes with their CPU and GPU usage
-pub fn take_processes_snapshot(system: &System, nvml: Option<&Nvml>) -> ProcessesSnapshot {
+/// Parses `usage_usec` out of cgroup v2's `cpu.stat`.
+fn _read_cgroup_cpu_stat_usec(cgroup: &Path) -> Option<u64> {
+    let contents = fs::read_to_string(cgroup.join("cpu.stat")).ok()?;
+    contents.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        if parts.next()? == "usage_usec" {
+            parts.next()?.parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+/// Takes a snapshot of all running processes with their CPU and GPU usage.
+/// `elapsed_secs` is the wall-clock time since the previous refresh, used to turn
+/// sysinfo's per-refresh disk byte deltas into per-second rates.
+pub fn take_processes_snapshot(
+    system: &System,
+    nvml: Option<&Nvml>,
+    elapsed_secs: f64,
+    gpu_util_watermarks: &mut GpuUtilWatermarks,
+    mode: AgentMode,
+) -> ProcessesSnapshot {
+    let cgroup_usage = match mode {
+        AgentMode::Slurm => _collect_cgroup_usage(),
+        AgentMode::Local => None,
+    };
+
+    // In Slurm mode, `system` was refreshed over the union of the job's cgroup PIDs and
+    // whatever it already had loaded (see `refresh_user_processes`), so it can still carry
+    // other users' processes from the node. Re-resolve the cgroup's member PIDs here and
+    // scope the snapshot to them so a shared-node Slurm snapshot only reports the job's own
+    // processes. When the cgroup can't be resolved, no filter is applied here, so the
+    // snapshot covers every process `system` holds, as in Local mode.
+    let scoped_pids: Option<std::collections::HashSet<Pid>> = match mode {
+        AgentMode::Slurm => _resolve_job_cgroup().map(|cgroup| _pids_from_cgroup(&cgroup).into_iter().collect()),
+        AgentMode::Local => None,
+    };
+
     // First, collect GPU usage information indexed by PID
     let gpu_usage_map = if let Some(nvml) = nvml {
-        _collect_gpu_usage(nvml)
+        _collect_gpu_usage(nvml, gpu_util_watermarks)
     } else {
         HashMap::new()
     };
-    
+
     // Get total CPU count to generate CPU indexes for active processes
     let cpu_count = system.cpus().len() as CPUIndex;
-    
+
     // Then iterate through system processes and combine CPU + GPU data
     let processes: Vec<ProcessInfo> = system
         .processes()
         .iter()
+        .filter(|(pid, _)| scoped_pids.as_ref().map_or(true, |pids| pids.contains(pid)))
         .map(|(pid, process)| {
             let pid_value = pid.as_u32() as crate::metrics::Pid;
             
             // Get GPU info for this process if it exists
-            let (gpu_usage_percent, gpu_memory_bytes, gpus_indexes) = 
+            let (gpu_usage_percent, gpu_memory_bytes, gpus_indexes, gpu_process_kind) =
                 gpu_usage_map.get(&(pid_value as u32))
-                    .map(|gpu_info| (gpu_info.0, gpu_info.1, gpu_info.2.clone()))
-                    .unwrap_or((0.0, 0, Vec::new()));
+                    .map(|gpu_info| (gpu_info.0, gpu_info.1, gpu_info.2.clone(), gpu_info.3))
+                    .unwrap_or((0.0, 0, Vec::new(), None));
             
             // Generate CPU indexes based on CPU usage
             // If process is using CPU, assume it could run on any CPU
@@ -70,7 +255,19 @@ pub fn take_processes_snapshot(system: &System, nvml: Option<&Nvml>) -> Processe
             } else {
                 Vec::new()
             };
-            
+
+            let disk_usage = process.disk_usage();
+            let disk_read_bytes_per_sec = if elapsed_secs > 0.0 {
+                disk_usage.read_bytes as f64 / elapsed_secs
+            } else {
+                0.0
+            };
+            let disk_write_bytes_per_sec = if elapsed_secs > 0.0 {
+                disk_usage.written_bytes as f64 / elapsed_secs
+            } else {
+                0.0
+            };
+
             ProcessInfo {
                 pid: pid_value,
                 name: process.name().to_str().map(|s| s.to_string()),
@@ -80,133 +277,144 @@ pub fn take_processes_snapshot(system: &System, nvml: Option<&Nvml>) -> Processe
                 gpu_memory_bytes,
                 cpus_indexes,
                 gpus_indexes,
+                disk_read_bytes: disk_usage.total_read_bytes,
+                disk_write_bytes: disk_usage.total_written_bytes,
+                disk_read_bytes_per_sec,
+                disk_write_bytes_per_sec,
+                gpu_process_kind,
             }
         })
-        .filter(|p| p.cpu_usage_percent > 0.0 || p.gpu_usage_percent > 0.0 || p.cpu_memory_bytes > 0)
+        .filter(|p| {
+            p.cpu_usage_percent > 0.0
+                || p.gpu_usage_percent > 0.0
+                || p.gpu_memory_bytes > 0
+                || p.cpu_memory_bytes > 0
+                || p.disk_read_bytes_per_sec > 0.0
+                || p.disk_write_bytes_per_sec > 0.0
+        })
         .collect();
     
-    ProcessesSnapshot { processes }
+    ProcessesSnapshot { processes, cgroup_usage }
 }
 
-/// Collects GPU usage information for all processes
-/// Returns: HashMap<u32, (gpu_usage_percent, gpu_memory_bytes, Vec<GPUIndex>)>
-fn _collect_gpu_usage(nvml: &Nvml) -> HashMap<u32, (f32, u64, Vec<GPUIndex>)> {
-    let mut gpu_usage: HashMap<u32, (f32, u64, Vec<GPUIndex>)> = HashMap::new();
-    let mut gpu_utilizations: HashMap<GPUIndex, f32> = HashMap::new();
-    let mut gpu_total_memory: HashMap<GPUIndex, u64> = HashMap::new();
-    
+/// Collects GPU usage information for all processes.
+/// Per-process utilization comes from NVML's real process-utilization sampler rather than
+/// a memory-share heuristic; the compute/graphics process lists are consulted for
+/// per-process memory footprint and to classify each PID as a compute, graphics, or
+/// (if present in both) `Both` context, then joined by PID.
+/// Returns: HashMap<u32, (gpu_usage_percent, gpu_memory_bytes, Vec<GPUIndex>, Option<GpuProcessKind>)>
+fn _collect_gpu_usage(
+    nvml: &Nvml,
+    gpu_util_watermarks: &mut GpuUtilWatermarks,
+) -> HashMap<u32, (f32, u64, Vec<GPUIndex>, Option<GpuProcessKind>)> {
+    let mut gpu_usage: HashMap<u32, (f32, u64, Vec<GPUIndex>, Option<GpuProcessKind>)> = HashMap::new();
+
     let device_count = match nvml.device_count() {
         Ok(count) => count,
         Err(_) => return gpu_usage,
     };
-    
-    // First pass: collect GPU utilizations and total process memory per GPU
+
     for gpu_index in 0..device_count {
         let device = match nvml.device_by_index(gpu_index) {
             Ok(dev) => dev,
             Err(_) => continue,
         };
-        
-        // Get GPU utilization
-        let gpu_util = match device.utilization_rates() {
-            Ok(util) => util.gpu as f32,
-            Err(_) => 0.0,
-        };
-        gpu_utilizations.insert(gpu_index, gpu_util);
-        
-        // Calculate total memory used by all processes on this GPU
-        let mut total_proc_memory = 0u64;
-        
+
+        // Per-process memory footprint and compute/graphics classification still come
+        // from the compute/graphics process lists.
+        let mut gpu_memory: HashMap<u32, u64> = HashMap::new();
+        let mut gpu_kind: HashMap<u32, GpuProcessKind> = HashMap::new();
+
         if let Ok(compute_procs) = device.running_compute_processes() {
             for proc_info in &compute_procs {
                 if let UsedGpuMemory::Used(bytes) = proc_info.used_gpu_memory {
-                    total_proc_memory += bytes;
+                    *gpu_memory.entry(proc_info.pid).or_insert(0) += bytes;
                 }
+                gpu_kind
+                    .entry(proc_info.pid)
+                    .and_modify(|kind| *kind = GpuProcessKind::Both)
+                    .or_insert(GpuProcessKind::Compute);
             }
         }
-        
+
         if let Ok(graphics_procs) = device.running_graphics_processes() {
             for proc_info in &graphics_procs {
                 if let UsedGpuMemory::Used(bytes) = proc_info.used_gpu_memory {
-                    total_proc_memory += bytes;
+                    *gpu_memory.entry(proc_info.pid).or_insert(0) += bytes;
                 }
+                gpu_kind
+                    .entry(proc_info.pid)
+                    .and_modify(|kind| *kind = GpuProcessKind::Both)
+                    .or_insert(GpuProcessKind::Graphics);
             }
         }
-        
-        gpu_total_memory.insert(gpu_index, total_proc_memory);
-    }
-    
-    // Second pass: collect process information and calculate per-process GPU usage
-    for gpu_index in 0..device_count {
-        let device = match nvml.device_by_index(gpu_index) {
-            Ok(dev) => dev,
-            Err(_) => continue,
-        };
-        
-        let gpu_util = gpu_utilizations.get(&gpu_index).copied().unwrap_or(0.0);
-        let total_memory = gpu_total_memory.get(&gpu_index).copied().unwrap_or(1);
-        
-        // Get compute processes running on this GPU
-        let compute_processes = match device.running_compute_processes() {
-            Ok(procs) => procs,
-            Err(_) => continue,
-        };
-        
-        for proc_info in compute_processes {
-            let pid = proc_info.pid;
-            let memory = match proc_info.used_gpu_memory {
-                UsedGpuMemory::Used(bytes) => bytes,
-                UsedGpuMemory::Unavailable => 0,
-            };
-            
-            // Estimate GPU usage based on memory proportion
-            let usage_percent = if total_memory > 0 {
-                (memory as f64 / total_memory as f64 * gpu_util as f64) as f32
-            } else {
-                0.0
-            };
-            
+
+        // Real per-process utilization, sampled since the last time we queried this device.
+        // `NotFound` means no samples are available yet, and e.g. `NotSupported` means the
+        // device doesn't offer per-process utilization at all (older cards, or accounting
+        // mode disabled) — either way, fall through with an empty sample set rather than
+        // skipping the device entirely, so the resident-memory/kind scan below still runs.
+        let last_seen_timestamp = gpu_util_watermarks.get(&gpu_index).copied().unwrap_or(0);
+        let samples = device.process_utilization_stats(last_seen_timestamp).unwrap_or_default();
+
+        if let Some(newest) = samples.iter().map(|s| s.timestamp).max() {
+            gpu_util_watermarks.insert(gpu_index, newest);
+        }
+
+        let sampled_pids: std::collections::HashSet<u32> = samples.iter().map(|s| s.pid).collect();
+
+        for sample in samples {
+            let pid = sample.pid;
+            let usage_percent = sample.sm_util as f32;
+            let memory = gpu_memory.get(&pid).copied().unwrap_or(0);
+            let kind = gpu_kind.get(&pid).copied();
+
             gpu_usage.entry(pid)
-                .and_modify(|(usage, mem, gpus)| {
+                .and_modify(|(usage, mem, gpus, existing_kind)| {
                     *usage += usage_percent;
                     *mem += memory;
                     if !gpus.contains(&gpu_index) {
                         gpus.push(gpu_index);
                     }
+                    *existing_kind = match (*existing_kind, kind) {
+                        (None, k) => k,
+                        (Some(a), Some(b)) if a != b => Some(GpuProcessKind::Both),
+                        (existing, _) => existing,
+                    };
                 })
-                .or_insert((usage_percent, memory, vec![gpu_index]));
+                .or_insert((usage_percent, memory, vec![gpu_index], kind));
         }
-        
-        // Also check graphics processes
-        let graphics_processes = match device.running_graphics_processes() {
-            Ok(procs) => procs,
-            Err(_) => continue,
-        };
-        
-        for proc_info in graphics_processes {
-            let pid = proc_info.pid;
-            let memory = match proc_info.used_gpu_memory {
-                UsedGpuMemory::Used(bytes) => bytes,
-                UsedGpuMemory::Unavailable => 0,
-            };
-            
-            let usage_percent = if total_memory > 0 {
-                (memory as f64 / total_memory as f64 * gpu_util as f64) as f32
-            } else {
-                0.0
-            };
-            
+
+        // A process can hold GPU memory (e.g. idle between training steps) without producing a
+        // utilization sample in this window. Keep it visible with a zeroed `sm_util` instead of
+        // dropping its memory footprint and classification because the sample and memory-scan
+        // PID sets don't match — this is also what surfaces idle graphics/X-server contexts so
+        // consumers can filter them out, since those rarely produce an sm_util sample either.
+        let mut resident_pids: Vec<u32> = gpu_memory.keys().chain(gpu_kind.keys()).copied().collect();
+        resident_pids.sort_unstable();
+        resident_pids.dedup();
+        for pid in resident_pids {
+            if sampled_pids.contains(&pid) {
+                continue;
+            }
+            let memory = gpu_memory.get(&pid).copied().unwrap_or(0);
+            let kind = gpu_kind.get(&pid).copied();
+
             gpu_usage.entry(pid)
-                .and_modify(|(usage, mem, gpus)| {
-                    *usage += usage_percent;
+                .and_modify(|(_, mem, gpus, existing_kind)| {
                     *mem += memory;
                     if !gpus.contains(&gpu_index) {
                         gpus.push(gpu_index);
                     }
+                    *existing_kind = match (*existing_kind, kind) {
+                        (None, k) => k,
+                        (Some(a), Some(b)) if a != b => Some(GpuProcessKind::Both),
+                        (existing, _) => existing,
+                    };
                 })
-                .or_insert((usage_percent, memory, vec![gpu_index]));
+                .or_insert((0.0, memory, vec![gpu_index], kind));
         }
     }
-    
+
     gpu_usage
 }
@@ -0,0 +1,7 @@
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum OutputFormat {
+    Files,
+    Ndjson,
+}
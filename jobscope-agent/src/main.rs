@@ -1,9 +1,15 @@
 mod collectors;
+mod format;
 mod metrics;
+mod mode;
 
+use std::time::Instant;
 use sysinfo::System;
 use nvml_wrapper::Nvml;
 use clap::Parser;
+use format::OutputFormat;
+use mode::AgentMode;
+use collectors::process::GpuUtilWatermarks;
 
 
 fn main() {
@@ -11,10 +17,12 @@ fn main() {
     let cli = Cli::parse();
     let output_folder = &cli.output;
     let period = cli.period;
+    let mode = cli.mode;
+    let format = cli.format;
 
     // Initialize sysinfo System
     let mut system = System::new_all();
-    
+
     // Initialize NVML for GPU monitoring
     let nvml = match Nvml::init() {
         Ok(nvml) => Some(nvml),
@@ -26,32 +34,59 @@ fn main() {
     };
 
     // First refresh to initialize counters
-    system.refresh_all();
+    system.refresh_cpu_usage();
+    system.refresh_memory();
+    collectors::process::refresh_user_processes(&mut system, mode);
+    let mut last_refresh = Instant::now();
+    let mut gpu_util_watermarks = GpuUtilWatermarks::new();
 
     // If not continuous, we need a small delay to get CPU usage
     if !cli.continuous {
         std::thread::sleep(std::time::Duration::from_millis(200));
-        system.refresh_all();
-        take_and_save_snapshot(&system, nvml.as_ref(), output_folder);
+        system.refresh_cpu_usage();
+        system.refresh_memory();
+        collectors::process::refresh_user_processes(&mut system, mode);
+        let elapsed_secs = last_refresh.elapsed().as_secs_f64();
+        take_and_save_snapshot(&system, nvml.as_ref(), output_folder, mode, elapsed_secs, &mut gpu_util_watermarks, format);
         return;
     }
 
     println!("Starting continuous monitoring with period {}s", period);
-    
+
     loop {
         // Wait for the period
         std::thread::sleep(std::time::Duration::from_secs_f64(period));
-        
+
         // Refresh and take snapshot
-        system.refresh_all();
-        if let Err(e) = take_and_save_snapshot(&system, nvml.as_ref(), output_folder) {
+        system.refresh_cpu_usage();
+        system.refresh_memory();
+        collectors::process::refresh_user_processes(&mut system, mode);
+        let elapsed_secs = last_refresh.elapsed().as_secs_f64();
+        last_refresh = Instant::now();
+        if let Err(e) = take_and_save_snapshot(&system, nvml.as_ref(), output_folder, mode, elapsed_secs, &mut gpu_util_watermarks, format) {
             eprintln!("Error taking snapshot: {}", e);
         }
     }
 }
 
-fn take_and_save_snapshot(system: &System, nvml: Option<&Nvml>, output_folder: &str) -> Result<(), Box<dyn std::error::Error>> {
-    match collectors::take_global_snapshot(system, nvml, output_folder) {
+fn take_and_save_snapshot(
+    system: &System,
+    nvml: Option<&Nvml>,
+    output_folder: &str,
+    mode: AgentMode,
+    elapsed_secs: f64,
+    gpu_util_watermarks: &mut GpuUtilWatermarks,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match collectors::take_global_snapshot(
+        system,
+        nvml,
+        output_folder,
+        mode,
+        elapsed_secs,
+        gpu_util_watermarks,
+        format,
+    ) {
         Ok(filepath) => {
             println!("Snapshot saved to: {}", filepath);
             Ok(())
@@ -75,4 +110,13 @@ struct Cli {
     /// Sampling period in seconds
     #[arg(short, long, default_value_t = 2.0)]
     period: f64,
+
+    /// Process scoping strategy: walk /proc by UID, or scope to the Slurm job's cgroup
+    #[arg(long, value_enum, default_value_t = AgentMode::Local)]
+    mode: AgentMode,
+
+    /// Snapshot output format: one pretty-printed file per tick, or one compact NDJSON line
+    /// appended per tick to a single rolling file per host
+    #[arg(long, value_enum, default_value_t = OutputFormat::Files)]
+    format: OutputFormat,
 }
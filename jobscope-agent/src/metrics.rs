@@ -11,6 +11,7 @@ pub struct Snapshot {
     pub cpus_snapshot: CPUsSnapshot,
     pub gpus_snapshot: GPUsSnapshot,
     pub processes_snapshot: ProcessesSnapshot,
+    pub disks_snapshot: DisksSnapshot,
 }
 
 #[derive(Serialize)]
@@ -27,6 +28,30 @@ pub struct GPUsSnapshot {
 #[derive(Serialize)]
 pub struct ProcessesSnapshot {
     pub processes: Vec<ProcessInfo>,
+    pub cgroup_usage: Option<CgroupUsage>,
+}
+
+#[derive(Serialize)]
+pub struct DisksSnapshot {
+    pub disks: Vec<DiskInfo>,
+}
+
+#[derive(Serialize)]
+pub struct DiskInfo {
+    pub mount_point: String,
+    pub name: Option<String>,
+    pub file_system: Option<String>,
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+    pub is_removable: bool,
+}
+
+/// Job-wide accounting read straight from the Slurm job's cgroup, rather than aggregated
+/// from individual processes, so it reflects the job's allocation rather than the whole node.
+#[derive(Serialize)]
+pub struct CgroupUsage {
+    pub memory: MemoryLoad,
+    pub cpu_usage_usec: u64,
 }
 
 #[derive(Serialize)]
@@ -48,6 +73,19 @@ pub struct GPUInfo {
     pub name: Option<String>,
     pub usage_percent: f32,
     pub memory_load: MemoryLoad,
+    pub temperature_celsius: Option<u32>,
+    pub power_usage_watts: Option<f32>,
+    pub power_limit_watts: Option<f32>,
+    pub fan_speed_percent: Option<u32>,
+    pub clocks: Option<GPUClocks>,
+}
+
+#[derive(Serialize)]
+pub struct GPUClocks {
+    pub graphics_mhz: u32,
+    pub sm_mhz: u32,
+    pub memory_mhz: u32,
+    pub video_mhz: u32,
 }
 
 #[derive(Serialize)]
@@ -63,5 +101,19 @@ pub struct ProcessInfo {
 
     pub cpus_indexes: Vec<CPUIndex>,
     pub gpus_indexes: Vec<GPUIndex>,
+
+    pub disk_read_bytes: u64,
+    pub disk_write_bytes: u64,
+    pub disk_read_bytes_per_sec: f64,
+    pub disk_write_bytes_per_sec: f64,
+
+    pub gpu_process_kind: Option<GpuProcessKind>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum GpuProcessKind {
+    Compute,
+    Graphics,
+    Both,
 }
     
\ No newline at end of file